@@ -0,0 +1,176 @@
+use crate::*;
+
+/// A grammar node: the shape of a parser, without any of its behavior.
+///
+/// This is the representation `named` and the `d_*` combinators build up
+/// alongside a real parser, so that a grammar assembled from this crate can
+/// eventually be turned back into something readable (see
+/// [`render_grammar`]) instead of staying locked inside opaque closures.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repr {
+    Tag(String),
+    Alt(Vec<Repr>),
+    Seq(Vec<Repr>),
+    Repeat(Box<Repr>),
+    Opt(Box<Repr>),
+    Named(String, Box<Repr>),
+}
+
+/// A parser paired with the `Repr` describing the grammar it matches.
+///
+/// Implements `Parser`, so it composes with everything in that trait; the
+/// `d_*` free functions are its equivalents of `tag`, `either`/`choice`,
+/// `pair`/`trio`, `many`, `opt` and `chain`/`infix`, each one also combining
+/// the `Repr`s of its arguments.
+pub struct Described<'a, O> {
+    parser: BoxedParser<'a, O>,
+    pub repr: Repr,
+}
+
+impl<'a, O> Described<'a, O> {
+    pub fn new<P>(parser: P, repr: Repr) -> Self
+    where
+        P: Fn(&'a str) -> ParseResult<'a, O> + 'a,
+    {
+        Described { parser: BoxedParser::new(parser), repr }
+    }
+}
+
+impl<'a, O> Parser<'a, O> for Described<'a, O> {
+    fn parse(&self, i: &'a str) -> ParseResult<'a, O> {
+        self.parser.parse(i)
+    }
+}
+
+/// Wraps a described parser with a name, so [`render_grammar`] emits a
+/// `name = ...;` rule for it and, anywhere it recurs, refers back to the name
+/// instead of re-expanding it (which is what keeps cyclic grammars from
+/// rendering forever).
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let digit = named("digit", d_choice(vec![d_tag("0"), d_tag("1")]));
+/// let number = named("number", d_many(digit));
+///
+/// assert_eq!(render_grammar(&number.repr), "digit = \"0\" | \"1\";\nnumber = { digit };");
+/// ```
+pub fn named<'a, O: 'a>(name: &str, p: Described<'a, O>) -> Described<'a, O> {
+    let repr = Repr::Named(name.to_string(), Box::new(p.repr.clone()));
+    Described::new(move |i| p.parse(i), repr)
+}
+
+/// See the free function [`tag`].
+pub fn d_tag<'a>(t: &'static str) -> Described<'a, &'a str> {
+    Described::new(tag(t), Repr::Tag(t.to_string()))
+}
+
+/// See the free function [`choice`]. Unlike `choice`, the alternatives don't
+/// need to share a concrete parser type, since `Described` already boxes
+/// its parser.
+pub fn d_choice<'a, O: 'a>(ps: Vec<Described<'a, O>>) -> Described<'a, O> {
+    let repr = Repr::Alt(ps.iter().map(|p| p.repr.clone()).collect());
+    Described::new(
+        move |i| {
+            let mut worst: Option<ParseError> = None;
+            for p in &ps {
+                match p.parse(i) {
+                    Ok(r) => return Ok(r),
+                    Err(e) => {
+                        if worst.as_ref().map_or(true, |w| e.offset > w.offset) {
+                            worst = Some(e);
+                        }
+                    }
+                }
+            }
+            Err(worst.unwrap_or_else(|| ParseError::new(i, ParserError::Choice)))
+        },
+        repr,
+    )
+}
+
+/// See the free function [`either`]. Unlike `either`, the two alternatives
+/// don't need to share a concrete parser type, since `Described` already
+/// boxes its parser.
+pub fn d_either<'a, O: 'a>(a: Described<'a, O>, b: Described<'a, O>) -> Described<'a, O> {
+    d_choice(vec![a, b])
+}
+
+/// See the free function [`pair`].
+pub fn d_pair<'a, A: 'a, B: 'a>(a: Described<'a, A>, b: Described<'a, B>) -> Described<'a, (A, B)> {
+    let repr = Repr::Seq(vec![a.repr.clone(), b.repr.clone()]);
+    Described::new(pair(move |i| a.parse(i), move |i| b.parse(i)), repr)
+}
+
+/// See the free function [`trio`].
+pub fn d_trio<'a, A: 'a, B: 'a, C: 'a>(
+    a: Described<'a, A>,
+    b: Described<'a, B>,
+    c: Described<'a, C>,
+) -> Described<'a, (A, B, C)> {
+    let repr = Repr::Seq(vec![a.repr.clone(), b.repr.clone(), c.repr.clone()]);
+    Described::new(trio(move |i| a.parse(i), move |i| b.parse(i), move |i| c.parse(i)), repr)
+}
+
+/// See the free function [`many`].
+pub fn d_many<'a, O: 'a>(p: Described<'a, O>) -> Described<'a, Vec<O>> {
+    let repr = Repr::Repeat(Box::new(p.repr.clone()));
+    Described::new(many(move |i| p.parse(i)), repr)
+}
+
+/// See the free function [`opt`].
+pub fn d_opt<'a, O: 'a>(p: Described<'a, O>) -> Described<'a, Option<O>> {
+    let repr = Repr::Opt(Box::new(p.repr.clone()));
+    Described::new(opt(move |i| p.parse(i)), repr)
+}
+
+/// See the free function [`chain`].
+pub fn d_chain<'a, S: 'a + Clone, O: 'a + Clone>(sep: Described<'a, S>, p: Described<'a, O>) -> Described<'a, Vec<O>> {
+    let repr = Repr::Opt(Box::new(Repr::Seq(vec![
+        p.repr.clone(),
+        Repr::Repeat(Box::new(Repr::Seq(vec![sep.repr.clone(), p.repr.clone()]))),
+        Repr::Opt(Box::new(sep.repr.clone())),
+    ])));
+    Described::new(chain(move |i| sep.parse(i), move |i| p.parse(i)), repr)
+}
+
+/// See the free function [`infix`].
+pub fn d_infix<'a, O: 'a, S: 'a>(p: Described<'a, O>, o: Described<'a, S>) -> Described<'a, (O, Vec<(S, O)>)> {
+    let repr = Repr::Seq(vec![
+        p.repr.clone(),
+        Repr::Repeat(Box::new(Repr::Seq(vec![o.repr.clone(), p.repr.clone()]))),
+    ]);
+    Described::new(infix(move |i| p.parse(i), move |i| o.parse(i)), repr)
+}
+
+fn render_node(repr: &Repr, seen: &mut Vec<String>, rules: &mut Vec<String>) -> String {
+    match repr {
+        Repr::Tag(s) => format!("{:?}", s),
+        Repr::Alt(items) => items.iter().map(|r| render_node(r, seen, rules)).collect::<Vec<_>>().join(" | "),
+        Repr::Seq(items) => items.iter().map(|r| render_node(r, seen, rules)).collect::<Vec<_>>().join(" "),
+        Repr::Repeat(inner) => format!("{{ {} }}", render_node(inner, seen, rules)),
+        Repr::Opt(inner) => format!("[ {} ]", render_node(inner, seen, rules)),
+        Repr::Named(name, inner) => {
+            if seen.contains(name) {
+                return name.clone();
+            }
+            seen.push(name.clone());
+            let body = render_node(inner, seen, rules);
+            rules.push(format!("{} = {};", name, body));
+            name.clone()
+        }
+    }
+}
+
+/// Renders a grammar built out of [`named`] and the `d_*` combinators as
+/// readable EBNF: one `name = ...;` rule per named node, each emitted once its
+/// body has been rendered (so the rules of named nodes nested inside another
+/// come before that node's own rule), with later recurrences of the same name
+/// referring back to it instead of re-expanding it.
+pub fn render_grammar(repr: &Repr) -> String {
+    let mut seen = Vec::new();
+    let mut rules = Vec::new();
+    render_node(repr, &mut seen, &mut rules);
+    rules.join("\n")
+}
@@ -1,11 +1,58 @@
-pub type ParseResult<'a, T> = Result<(&'a str, T), (&'a str, ParserError)>;
+mod parser;
+pub use parser::*;
+
+mod grammar;
+pub use grammar::*;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_PARSER_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub type ParseResult<'a, T> = Result<(&'a str, T), ParseError<'a>>;
+
+/// The error half of a `ParseResult`.
+///
+/// Besides the `kind` of failure and the `input` slice where it happened,
+/// this carries an `offset` (the byte distance from the start of whatever
+/// input the failing combinator was originally called with) and a
+/// `context` trail built up by the `context` combinator as the error
+/// bubbles back out through nested parsers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError<'a> {
+    pub input: &'a str,
+    pub offset: usize,
+    pub kind: ParserError,
+    pub context: Vec<(&'static str, ParserError)>,
+}
+
+impl<'a> ParseError<'a> {
+    /// Builds a fresh, offset-zero error with no context trail.
+    pub fn new(input: &'a str, kind: ParserError) -> Self {
+        ParseError { input, offset: 0, kind, context: Vec::new() }
+    }
+
+    /// Shifts `offset` forward to account for input already consumed by an
+    /// outer combinator before this error occurred.
+    fn advance(mut self, consumed: usize) -> Self {
+        self.offset += consumed;
+        self
+    }
+}
+
+/// Computes how many bytes of `start` were consumed to arrive at `end`,
+/// using the same pointer-arithmetic trick as `recognize`.
+fn consumed(start: &str, end: &str) -> usize {
+    end.as_ptr() as usize - start.as_ptr() as usize
+}
 
 /// Recognizes a fixed string pattern.
 ///
 /// If the input data matches the first argument, it will return a successful
 /// value containing the argument itself.
 ///
-/// Otherwise it returns `Err((_, ParserError::Tag))`
+/// Otherwise it returns `Err(ParseError::new(_, ParserError::Tag))`
 ///
 /// # Example
 /// ```rust
@@ -14,13 +61,111 @@ pub type ParseResult<'a, T> = Result<(&'a str, T), (&'a str, ParserError)>;
 /// let parser = tag("function");
 ///
 /// assert_eq!(parser("function hello"), Ok((" hello", "function")));
-/// assert_eq!(parser("Something else"), Err(("Something else", ParserError::Tag)));
-/// assert_eq!(parser(""), Err(("", ParserError::Tag)));
+/// assert_eq!(parser("Something else"), Err(ParseError::new("Something else", ParserError::Tag)));
+/// assert_eq!(parser(""), Err(ParseError::new("", ParserError::Tag)));
 /// ```
 pub fn tag(tag: &'static str) -> impl Fn(&str) -> ParseResult<&str> {
     move |i| match i.starts_with(tag) {
         true => Ok((&i[tag.len()..], tag)),
-        false => Err((i, ParserError::Tag)),
+        false => Err(ParseError::new(i, ParserError::Tag)),
+    }
+}
+
+/// Matches a single character that satisfies a predicate.
+///
+/// # Example
+/// ```
+/// use comb::*;
+///
+/// let parser = satisfy(|c: char| c.is_alphabetic());
+///
+/// assert_eq!(parser("abc"), Ok(("bc", 'a')));
+/// assert_eq!(parser("123"), Err(ParseError::new("123", ParserError::Satisfy)));
+/// ```
+pub fn satisfy<'a, P>(p: P) -> impl Fn(&'a str) -> ParseResult<char>
+where
+    P: Fn(char) -> bool,
+{
+    move |i| match i.chars().next() {
+        Some(c) if p(c) => Ok((&i[c.len_utf8()..], c)),
+        _ => Err(ParseError::new(i, ParserError::Satisfy)),
+    }
+}
+
+/// Matches a single character, as long as it's one of the characters in
+/// `set`. Works on chars, not bytes, so non-ASCII sets work too.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = one_of("aeiou");
+///
+/// assert_eq!(parser("oh noes"), Ok(("h noes", 'o')));
+/// assert_eq!(parser("nah"), Err(ParseError::new("nah", ParserError::OneOf)));
+/// ```
+pub fn one_of<'a>(set: &'a str) -> impl Fn(&'a str) -> ParseResult<char> {
+    move |i| match i.chars().next() {
+        Some(c) if set.chars().any(|s| s == c) => Ok((&i[c.len_utf8()..], c)),
+        _ => Err(ParseError::new(i, ParserError::OneOf)),
+    }
+}
+
+/// The opposite of `one_of`: matches a single character, as long as it's
+/// none of the characters in `set`.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = none_of("aeiou");
+///
+/// assert_eq!(parser("nah"), Ok(("ah", 'n')));
+/// assert_eq!(parser("oh noes"), Err(ParseError::new("oh noes", ParserError::NoneOf)));
+/// ```
+pub fn none_of<'a>(set: &'a str) -> impl Fn(&'a str) -> ParseResult<char> {
+    move |i| match i.chars().next() {
+        Some(c) if !set.chars().any(|s| s == c) => Ok((&i[c.len_utf8()..], c)),
+        _ => Err(ParseError::new(i, ParserError::NoneOf)),
+    }
+}
+
+/// Matches a single, specific literal character.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = char('x');
+///
+/// assert_eq!(parser("xyz"), Ok(("yz", 'x')));
+/// assert_eq!(parser("abc"), Err(ParseError::new("abc", ParserError::Char)));
+/// ```
+pub fn char(c: char) -> impl Fn(&str) -> ParseResult<char> {
+    move |i| match i.chars().next() {
+        Some(x) if x == c => Ok((&i[x.len_utf8()..], x)),
+        _ => Err(ParseError::new(i, ParserError::Char)),
+    }
+}
+
+/// Like `tag`, but case-insensitive (ASCII only). Still returns the actual
+/// matched slice from the input, not `s` itself, so the original casing is
+/// preserved.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = tag_no_case("function");
+///
+/// assert_eq!(parser("FUNCTION hello"), Ok((" hello", "FUNCTION")));
+/// assert_eq!(parser("Function(x)"), Ok(("(x)", "Function")));
+/// assert_eq!(parser("nope"), Err(ParseError::new("nope", ParserError::TagNoCase)));
+/// ```
+pub fn tag_no_case(s: &'static str) -> impl Fn(&str) -> ParseResult<&str> {
+    move |i| match i.get(..s.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(s) => Ok((&i[s.len()..], prefix)),
+        _ => Err(ParseError::new(i, ParserError::TagNoCase)),
     }
 }
 
@@ -36,11 +181,11 @@ pub fn tag(tag: &'static str) -> impl Fn(&str) -> ParseResult<&str> {
 /// let parser = value(tag("Hello, world!"), "Hallo welt");
 ///
 /// assert_eq!(parser("Hello, world!"), Ok(("", "Hallo welt")));
-/// assert_eq!(parser("Bonjour le monde"), Err(("Bonjour le monde", ParserError::Tag)));
+/// assert_eq!(parser("Bonjour le monde"), Err(ParseError::new("Bonjour le monde", ParserError::Tag)));
 /// ```
 pub fn value<'a, P, R, V>(p: P, v: V) -> impl Fn(&'a str) -> ParseResult<V>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
     V: Copy,
 {
     move |i| p(i).map(|(i, _)| (i, v))
@@ -59,11 +204,11 @@ where
 /// let parser = map(tag("1"), |s| s.parse::<i32>().unwrap());
 ///
 /// assert_eq!(parser("1"), Ok(("", 1)));
-/// assert_eq!(parser("2"), Err(("2", ParserError::Tag)));
+/// assert_eq!(parser("2"), Err(ParseError::new("2", ParserError::Tag)));
 /// ```
 pub fn map<'a, P, F, A, B>(p: P, f: F) -> impl Fn(&'a str) -> ParseResult<B>
 where
-    P: Fn(&'a str) -> ParseResult<A>,
+    P: Fn(&'a str) -> ParseResult<'a, A>,
     F: Fn(A) -> B,
 {
     move |i| p(i).map(|(i, r)| (i, f(r)))
@@ -79,14 +224,86 @@ where
 /// let parser = map_res(take_while(|c| c.is_alphanumeric()), |s| s.parse::<i32>());
 ///
 /// assert_eq!(parser("123"), Ok(("", 123)));
-/// assert_eq!(parser("abc"), Err(("", ParserError::MapRes)));
+/// let err = parser("abc").unwrap_err();
+/// assert_eq!(err.kind, ParserError::MapRes);
+/// assert_eq!(err.offset, 3);
 /// ```
 pub fn map_res<'a, P, F, A, B, E>(p: P, f: F) -> impl Fn(&'a str) -> ParseResult<B>
 where
-    P: Fn(&'a str) -> ParseResult<A>,
+    P: Fn(&'a str) -> ParseResult<'a, A>,
     F: Fn(A) -> Result<B, E>,
 {
-    move |i| p(i).and_then(|(i, r)| f(r).map(|r| (i, r)).or(Err((i, ParserError::MapRes))))
+    move |i| {
+        p(i).and_then(|(i2, r)| {
+            f(r)
+                .map(|r| (i2, r))
+                .map_err(|_| ParseError::new(i2, ParserError::MapRes).advance(consumed(i, i2)))
+        })
+    }
+}
+
+/// Runs `p`, then uses its result to build and run a second parser against
+/// whatever input is left. Unlike `map`/`map_res`, the *next* parser can
+/// depend on a previously parsed value instead of being fixed up front --
+/// which is exactly what's needed for a closing tag that has to match the
+/// tag that opened it.
+///
+/// # Example
+/// ```
+/// use comb::*;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Element<'a> {
+///     name: &'a str,
+///     attributes: Vec<(&'a str, &'a str)>,
+///     children: &'a str,
+/// }
+///
+/// fn ident(i: &str) -> ParseResult<&str> {
+///     take_while(|c| c.is_alphanumeric())(i)
+/// }
+///
+/// // `tag` only matches `&'static str`s, so matching a closing tag against a
+/// // name parsed at runtime needs its own little parser instead.
+/// fn exact<'a>(s: &'a str) -> impl Fn(&'a str) -> ParseResult<'a, &'a str> {
+///     move |i: &'a str| match i.starts_with(s) {
+///         true => Ok((&i[s.len()..], s)),
+///         false => Err(ParseError::new(i, ParserError::Tag)),
+///     }
+/// }
+///
+/// fn attribute(i: &str) -> ParseResult<(&str, &str)> {
+///     pair(left(w(ident), tag("=\"")), left(take_while(|c| c != '"'), tag("\"")))(i)
+/// }
+///
+/// fn open_tag(i: &str) -> ParseResult<(&str, Option<(&str, &str)>)> {
+///     pair(right(tag("<"), ident), left(opt(attribute), tag(">")))(i)
+/// }
+///
+/// fn element(i: &str) -> ParseResult<Element> {
+///     and_then(open_tag, |(name, attrs)| {
+///         move |i| {
+///             left(take_until("</"), trio(tag("</"), exact(name), tag(">")))(i)
+///                 .map(|(i, children)| (i, Element { name, attributes: attrs.into_iter().collect(), children }))
+///         }
+///     })(i)
+/// }
+///
+/// let (rest, el) = element("<a href=\"x\">hi</a>").unwrap();
+/// assert_eq!(rest, "");
+/// assert_eq!(el, Element { name: "a", attributes: vec![("href", "x")], children: "hi" });
+///
+/// assert_eq!(element("<a>hi</a>").unwrap().1, Element { name: "a", attributes: vec![], children: "hi" });
+///
+/// assert!(element("<a>hi</b>").is_err());
+/// ```
+pub fn and_then<'a, P, F, A, B, Q>(p: P, f: F) -> impl Fn(&'a str) -> ParseResult<B>
+where
+    P: Fn(&'a str) -> ParseResult<'a, A>,
+    F: Fn(A) -> Q,
+    Q: Fn(&'a str) -> ParseResult<'a, B>,
+{
+    move |i| p(i).and_then(|(i2, r)| f(r)(i2).map_err(|e| e.advance(consumed(i, i2))))
 }
 
 /// Makes the inner parser optional by swallowing errors and turning them into a
@@ -103,7 +320,7 @@ where
 /// ```
 pub fn opt<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<Option<R>>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |i| p(i).and_then(|(i, r)| Ok((i, Some(r)))).or(Ok((i, None)))
 }
@@ -118,14 +335,21 @@ where
 /// let parser = pair(tag("hello "), tag("world"));
 ///
 /// assert_eq!(parser("hello world"), Ok(("", ("hello ", "world"))));
-/// assert_eq!(parser("oh noes"), Err(("oh noes", ParserError::Tag)));
+/// assert_eq!(parser("oh noes"), Err(ParseError::new("oh noes", ParserError::Tag)));
+/// assert_eq!(parser("hello there").unwrap_err().offset, 6);
 /// ```
 pub fn pair<'a, A, B, X, Y>(a: A, b: B) -> impl Fn(&'a str) -> ParseResult<(X, Y)>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
 {
-    move |i| a(i).and_then(|(i, r1)| b(i).map(|(i, r2)| (i, (r1, r2))))
+    move |i| {
+        a(i).and_then(|(i2, r1)| {
+            b(i2)
+                .map(|(i3, r2)| (i3, (r1, r2)))
+                .map_err(|e| e.advance(consumed(i, i2)))
+        })
+    }
 }
 
 /// What's better than a pair? You got it: a trio.
@@ -137,15 +361,25 @@ where
 /// let parser = trio(tag("ein "), tag("zwei "), tag("drei"));
 ///
 /// assert_eq!(parser("ein zwei drei"), Ok(("", ("ein ", "zwei ", "drei"))));
-/// assert_eq!(parser("one two three"), Err(("one two three", ParserError::Tag)));
+/// assert_eq!(parser("one two three"), Err(ParseError::new("one two three", ParserError::Tag)));
 /// ```
 pub fn trio<'a, A, B, C, X, Y, Z>(a: A, b: B, c: C) -> impl Fn(&'a str) -> ParseResult<(X, Y, Z)>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
-    C: Fn(&'a str) -> ParseResult<Z>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
+    C: Fn(&'a str) -> ParseResult<'a, Z>,
 {
-    move |i| a(i).and_then(|(i, x)| b(i).and_then(|(i, y)| c(i).map(|(i, z)| (i, (x, y, z)))))
+    move |i| {
+        a(i).and_then(|(i2, x)| {
+            b(i2)
+                .map_err(|e| e.advance(consumed(i, i2)))
+                .and_then(|(i3, y)| {
+                    c(i3)
+                        .map(|(i4, z)| (i4, (x, y, z)))
+                        .map_err(|e| e.advance(consumed(i, i3)))
+                })
+        })
+    }
 }
 
 /// Just like the pair combinator, but it throws away the result of the parser
@@ -158,14 +392,22 @@ where
 /// let parser = right(tag("not me "), tag("me"));
 ///
 /// assert_eq!(parser("not me me"), Ok(("", "me")));
-/// assert_eq!(parser("not me you"), Err(("you", ParserError::Tag)));
+/// let err = parser("not me you").unwrap_err();
+/// assert_eq!(err.kind, ParserError::Tag);
+/// assert_eq!(err.offset, 7);
 /// ```
 pub fn right<'a, A, B, X, Y>(a: A, b: B) -> impl Fn(&'a str) -> ParseResult<Y>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
 {
-    move |i| a(i).and_then(|(i, _)| b(i).map(|(i, r2)| (i, r2)))
+    move |i| {
+        a(i).and_then(|(i2, _)| {
+            b(i2)
+                .map(|(i3, r2)| (i3, r2))
+                .map_err(|e| e.advance(consumed(i, i2)))
+        })
+    }
 }
 
 /// We already have a right combinator. Guess what's next? The left. Balanced,
@@ -178,14 +420,20 @@ where
 /// let parser = left(tag("me"), tag("you"));
 ///
 /// assert_eq!(parser("meyou"), Ok(("", "me")));
-/// assert_eq!(parser("youme"), Err(("youme", ParserError::Tag)));
+/// assert_eq!(parser("youme"), Err(ParseError::new("youme", ParserError::Tag)));
 /// ```
 pub fn left<'a, A, B, X, Y>(a: A, b: B) -> impl Fn(&'a str) -> ParseResult<X>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
 {
-    move |i| a(i).and_then(|(i, r1)| b(i).map(|(i, _)| (i, r1)))
+    move |i| {
+        a(i).and_then(|(i2, r1)| {
+            b(i2)
+                .map(|(i3, _)| (i3, r1))
+                .map_err(|e| e.advance(consumed(i, i2)))
+        })
+    }
 }
 
 /// Same as left and right, but now it rejects both tokens that bookend the one
@@ -198,15 +446,25 @@ where
 /// let parser = middle(tag("("), tag("secret"), tag(")"));
 ///
 /// assert_eq!(parser("(secret)"), Ok(("", "secret")));
-/// assert_eq!(parser("secret"), Err(("secret", ParserError::Tag)));
+/// assert_eq!(parser("secret"), Err(ParseError::new("secret", ParserError::Tag)));
 /// ```
 pub fn middle<'a, A, B, C, X, Y, Z>(a: A, b: B, c: C) -> impl Fn(&'a str) -> ParseResult<Y>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
-    C: Fn(&'a str) -> ParseResult<Z>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
+    C: Fn(&'a str) -> ParseResult<'a, Z>,
 {
-    move |i| a(i).and_then(|(i, _)| b(i).and_then(|(i, r2)| c(i).map(|(i, _)| (i, r2))))
+    move |i| {
+        a(i).and_then(|(i2, _)| {
+            b(i2)
+                .map_err(|e| e.advance(consumed(i, i2)))
+                .and_then(|(i3, r2)| {
+                    c(i3)
+                        .map(|(i4, _)| (i4, r2))
+                        .map_err(|e| e.advance(consumed(i, i3)))
+                })
+        })
+    }
 }
 
 /// Takes the result of the outermost parsers and rejects the middle. Useful for
@@ -219,15 +477,27 @@ where
 /// let parser = outer(tag("a"), tag(","), tag("b"));
 ///
 /// assert_eq!(parser("a,b"), Ok(("", ("a", "b"))));
-/// assert_eq!(parser("a+b"), Err(("+b", ParserError::Tag)));
+/// let err = parser("a+b").unwrap_err();
+/// assert_eq!(err.kind, ParserError::Tag);
+/// assert_eq!(err.offset, 1);
 /// ```
 pub fn outer<'a, A, B, C, X, Y, Z>(a: A, b: B, c: C) -> impl Fn(&'a str) -> ParseResult<(X, Z)>
 where
-    A: Fn(&'a str) -> ParseResult<X>,
-    B: Fn(&'a str) -> ParseResult<Y>,
-    C: Fn(&'a str) -> ParseResult<Z>,
+    A: Fn(&'a str) -> ParseResult<'a, X>,
+    B: Fn(&'a str) -> ParseResult<'a, Y>,
+    C: Fn(&'a str) -> ParseResult<'a, Z>,
 {
-    move |i| a(i).and_then(|(i, x)| b(i).and_then(|(i, _)| c(i).map(|(i, z)| (i, (x, z)))))
+    move |i| {
+        a(i).and_then(|(i2, x)| {
+            b(i2)
+                .map_err(|e| e.advance(consumed(i, i2)))
+                .and_then(|(i3, _)| {
+                    c(i3)
+                        .map(|(i4, z)| (i4, (x, z)))
+                        .map_err(|e| e.advance(consumed(i, i3)))
+                })
+        })
+    }
 }
 
 /// Tries to match either one of the parsers and returns the sucessful one.
@@ -240,12 +510,12 @@ where
 ///
 /// assert_eq!(parser("a"), Ok(("", "a")));
 /// assert_eq!(parser("b"), Ok(("", "b")));
-/// assert_eq!(parser("c"), Err(("c", ParserError::Tag)));
+/// assert_eq!(parser("c"), Err(ParseError::new("c", ParserError::Tag)));
 /// ```
 pub fn either<'a, A, B, R>(a: A, b: B) -> impl Fn(&'a str) -> ParseResult<R>
 where
-    A: Fn(&'a str) -> ParseResult<R>,
-    B: Fn(&'a str) -> ParseResult<R>,
+    A: Fn(&'a str) -> ParseResult<'a, R>,
+    B: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |i| a(i).or_else(|_| b(i))
 }
@@ -253,6 +523,10 @@ where
 /// Exactly like either, but in this case you can have as many choices as you
 /// need (as long as they have the same type).
 ///
+/// When every branch fails, the error reported is the one from whichever
+/// branch consumed the most input before failing, since that's usually the
+/// branch the author actually intended to match.
+///
 /// # Example
 /// ```
 /// use comb::*;
@@ -262,18 +536,26 @@ where
 /// assert_eq!(parser("1"), Ok(("", "1")));
 /// assert_eq!(parser("2"), Ok(("", "2")));
 /// assert_eq!(parser("3"), Ok(("", "3")));
-/// assert_eq!(parser("4"), Err(("4", ParserError::Choice)));
+/// assert_eq!(parser("4"), Err(ParseError::new("4", ParserError::Tag)));
 /// ```
 pub fn choice<'a, S, P, R>(ps: S) -> impl Fn(&'a str) -> ParseResult<R>
 where
     S: AsRef<[P]>,
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |i| {
-        AsRef::as_ref(&ps)
-            .iter()
-            .find_map(|p| p(i).ok())
-            .ok_or((i, ParserError::Choice))
+        let mut worst: Option<ParseError> = None;
+        for p in AsRef::as_ref(&ps) {
+            match p(i) {
+                Ok(r) => return Ok(r),
+                Err(e) => {
+                    if worst.as_ref().map_or(true, |w| e.offset > w.offset) {
+                        worst = Some(e);
+                    }
+                }
+            }
+        }
+        Err(worst.unwrap_or_else(|| ParseError::new(i, ParserError::Choice)))
     }
 }
 
@@ -287,17 +569,17 @@ where
 ///
 /// assert_eq!(parser("123"), Ok(("", "123")));
 /// assert_eq!(parser("456"), Ok(("", "456")));
-/// assert_eq!(parser("abc"), Err(("abc", ParserError::TakeWhile)));
+/// assert_eq!(parser("abc"), Err(ParseError::new("abc", ParserError::TakeWhile)));
 /// ```
 pub fn take_while<'a, P>(p: P) -> impl Fn(&'a str) -> ParseResult<&str>
 where
     P: Copy + Fn(char) -> bool,
 {
     move |i| match i.find(|c| !p(c)) {
-        Some(0) => Err((i, ParserError::TakeWhile)),
+        Some(0) => Err(ParseError::new(i, ParserError::TakeWhile)),
         Some(x) => Ok((&i[x..], &i[..x])),
         None if i.len() > 0 => Ok((&i[i.len()..], i)),
-        None => Err((i, ParserError::TakeWhile)),
+        None => Err(ParseError::new(i, ParserError::TakeWhile)),
     }
 }
 
@@ -331,11 +613,11 @@ pub fn take_until<'a>(p: &'a str) -> impl Fn(&'a str) -> ParseResult<&str> {
 /// let parser = peek(tag("The future"));
 ///
 /// assert_eq!(parser("The future"), Ok(("The future", "The future")));
-/// assert_eq!(parser("Not the future"), Err(("Not the future", ParserError::Tag)));
+/// assert_eq!(parser("Not the future"), Err(ParseError::new("Not the future", ParserError::Tag)));
 /// ```
 pub fn peek<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<R>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |i| p(i).map(|(_, o)| (i, o))
 }
@@ -352,11 +634,11 @@ where
 /// let parser = recognize(pair(tag("badger"), tag("badger")));
 ///
 /// assert_eq!(parser("badgerbadger"), Ok(("", "badgerbadger")));
-/// assert_eq!(parser("mushroom"), Err(("mushroom", ParserError::Tag)));
+/// assert_eq!(parser("mushroom"), Err(ParseError::new("mushroom", ParserError::Tag)));
 /// ```
-pub fn recognize<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<&'a str>
+pub fn recognize<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<'a, &'a str>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |i| p(i).map(|(i2, _)| (i2, &i[..(i2.as_ptr() as usize - i.as_ptr() as usize)]))
 }
@@ -370,16 +652,16 @@ where
 /// let parser = check(take_until("-"), |a| a.len() == 3);
 ///
 /// assert_eq!(parser("yes-"), Ok(("-", "yes")));
-/// assert_eq!(parser("no-"), Err(("no-", ParserError::Check)));
+/// assert_eq!(parser("no-"), Err(ParseError::new("no-", ParserError::Check)));
 /// ```
 pub fn check<'a, P, R, F>(p: P, f: F) -> impl Fn(&'a str) -> ParseResult<R>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
     F: Fn(&R) -> bool,
 {
     move |i| match p(i) {
         Ok((i, r)) if f(&r) => Ok((i, r)),
-        _ => Err((i, ParserError::Check)),
+        _ => Err(ParseError::new(i, ParserError::Check)),
     }
 }
 
@@ -401,7 +683,7 @@ where
 /// ```
 pub fn many<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<Vec<R>>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     move |mut i| {
         let mut r = Vec::new();
@@ -413,6 +695,61 @@ where
     }
 }
 
+/// Memoizes a parser so re-visiting the same input position returns a cached
+/// result instead of re-running it.
+///
+/// Grammars built from `choice`, `either` and `many` can re-parse the same
+/// position over and over while backtracking through ambiguous input, which
+/// degrades to quadratic or exponential time. `memo` wraps a parser with a
+/// cache keyed by `(parser_id, position)` — the position is just the input
+/// slice's pointer, which is stable for as long as it keeps being borrowed
+/// from the same source string — so a given position is only ever parsed
+/// once.
+///
+/// This only pays off if `p` is pure: same input, same result, no side
+/// effects, since a cache hit skips calling it entirely. The cache belongs to
+/// the returned parser and is only valid for one top-level parse; build a
+/// fresh `memo(...)` for each parse rather than reusing one across unrelated
+/// input strings.
+///
+/// # Example
+/// ```
+/// use comb::*;
+///
+/// let input = "badger";
+/// let parser = memo(tag("badger"));
+///
+/// assert_eq!(parser(input), Ok(("", "badger")));
+/// assert_eq!(parser(input), Ok(("", "badger")));
+///
+/// let parser = memo(tag("badger"));
+/// assert_eq!(parser("not badger").unwrap_err().kind, ParserError::Tag);
+/// ```
+pub fn memo<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<'a, R>
+where
+    P: Fn(&'a str) -> ParseResult<'a, R>,
+    R: Clone,
+{
+    let id = NEXT_PARSER_ID.fetch_add(1, Ordering::Relaxed);
+    let cache: RefCell<HashMap<(usize, usize), Result<(usize, R), ParseError<'a>>>> = RefCell::new(HashMap::new());
+    move |i| {
+        let key = (id, i.as_ptr() as usize);
+        if let Some(cached) = cache.borrow().get(&key).cloned() {
+            return match cached {
+                Ok((len, r)) => Ok((&i[len..], r)),
+                Err(e) => Err(e),
+            };
+        }
+        let result = p(i);
+        let cached = match &result {
+            Ok((rest, r)) => Ok((consumed(i, rest), r.clone())),
+            Err(e) => Err(e.clone()),
+        };
+        cache.borrow_mut().insert(key, cached);
+        result
+    }
+}
+
 /// Matches a chain of repetitions linked (got it?) by a certain token or
 /// combination of tokens.
 ///
@@ -429,8 +766,8 @@ where
 /// ```
 pub fn chain<'a, S, P, R1, R2>(sep: S, p: P) -> impl Fn(&'a str) -> ParseResult<Vec<R2>>
 where
-    S: Fn(&'a str) -> ParseResult<R1>,
-    P: Fn(&'a str) -> ParseResult<R2>,
+    S: Fn(&'a str) -> ParseResult<'a, R1>,
+    P: Fn(&'a str) -> ParseResult<'a, R2>,
     R1: Clone,
     R2: Clone,
 {
@@ -452,8 +789,8 @@ where
 /// ```
 pub fn infix<'a, P, O, R, S>(p: P, o: O) -> impl Fn(&'a str) -> ParseResult<(R, Vec<(S, R)>)>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
-    O: Fn(&'a str) -> ParseResult<S>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
+    O: Fn(&'a str) -> ParseResult<'a, S>,
 {
     move |i| pair(&p, many(pair(w(&o), &p)))(i)
 }
@@ -471,8 +808,8 @@ where
 /// ```
 pub fn prefix<'a, P, Q, X, Y>(p: P, q: Q) -> impl Fn(&'a str) -> ParseResult<(Vec<X>, Y)>
 where
-    P: Fn(&'a str) -> ParseResult<X>,
-    Q: Fn(&'a str) -> ParseResult<Y>,
+    P: Fn(&'a str) -> ParseResult<'a, X>,
+    Q: Fn(&'a str) -> ParseResult<'a, Y>,
 {
     move |i| pair(many(w(&p)), &q)(i)
 }
@@ -486,11 +823,11 @@ where
 /// let parser = boxed(tag("thing"));
 ///
 /// assert_eq!(parser("thing"), Ok(("", Box::new("thing"))));
-/// assert_eq!(parser("not thing"), Err(("not thing", ParserError::Tag)));
+/// assert_eq!(parser("not thing"), Err(ParseError::new("not thing", ParserError::Tag)));
 /// ```
 pub fn boxed<'a, P, R>(i: P) -> impl Fn(&'a str) -> ParseResult<Box<R>>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     map(i, Box::new)
 }
@@ -527,12 +864,12 @@ pub fn double<'a>(i: &'a str) -> ParseResult<f64> {
 /// use comb::*;
 ///
 /// assert_eq!(eoi(""), Ok(("", "")));
-/// assert_eq!(eoi("not the end"), Err(("not the end", ParserError::Eof)));
+/// assert_eq!(eoi("not the end"), Err(ParseError::new("not the end", ParserError::Eof)));
 /// ```
 pub fn eoi(i: &str) -> ParseResult<&str> {
     match i.is_empty() {
         true => Ok((i, "")),
-        false => Err((i, ParserError::Eof)),
+        false => Err(ParseError::new(i, ParserError::Eof)),
     }
 }
 
@@ -553,7 +890,7 @@ pub fn eoi(i: &str) -> ParseResult<&str> {
 pub fn whitespace<'a>(i: &str) -> ParseResult<&str> {
     match i.find(|c: char| !c.is_whitespace()) {
         Some(x) => Ok((&i[x..], &i[..x])),
-        _ => Ok(("", i)),
+        _ => Ok((&i[i.len()..], i)),
     }
 }
 
@@ -570,12 +907,74 @@ pub fn whitespace<'a>(i: &str) -> ParseResult<&str> {
 /// ```
 pub fn w<'a, P, R>(p: P) -> impl Fn(&'a str) -> ParseResult<R>
 where
-    P: Fn(&'a str) -> ParseResult<R>,
+    P: Fn(&'a str) -> ParseResult<'a, R>,
 {
     right(whitespace, p)
 }
 
-#[derive(Debug, PartialEq)]
+/// Tags a parser's failure with a label, pushing `(label, kind)` onto the
+/// error's `context` trail. Wrapping nested parsers in `context` turns a bare
+/// `ParserError::Tag` into a readable trail such as "while parsing exponent"
+/// -> "while parsing number".
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = context("digits", take_while(|c| c.is_numeric()));
+///
+/// assert_eq!(parser("123"), Ok(("", "123")));
+///
+/// let err = parser("abc").unwrap_err();
+/// assert_eq!(err.kind, ParserError::TakeWhile);
+/// assert_eq!(err.context, vec![("digits", ParserError::TakeWhile)]);
+/// ```
+pub fn context<'a, P, R>(label: &'static str, p: P) -> impl Fn(&'a str) -> ParseResult<R>
+where
+    P: Fn(&'a str) -> ParseResult<'a, R>,
+{
+    move |i| {
+        p(i).map_err(|mut e| {
+            e.context.push((label, e.kind.clone()));
+            e
+        })
+    }
+}
+
+/// Renders a parse error against the original source it was parsed from, as a
+/// human-readable `line:column` pointer followed by its context trail.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = context("greeting", tag("hello"));
+/// let source = "hi\nworld";
+/// let err = parser(source).unwrap_err();
+///
+/// assert_eq!(render_error(source, &err), "1:1: Tag\n  while parsing greeting");
+/// ```
+pub fn render_error(source: &str, error: &ParseError) -> String {
+    let mut line = 1;
+    let mut column = 1;
+    for c in source[..error.offset.min(source.len())].chars() {
+        match c {
+            '\n' => {
+                line += 1;
+                column = 1;
+            }
+            _ => column += 1,
+        }
+    }
+
+    let mut message = format!("{}:{}: {:?}", line, column, error.kind);
+    for (label, _) in &error.context {
+        message.push_str(&format!("\n  while parsing {}", label));
+    }
+    message
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParserError {
     Check,
     Choice,
@@ -583,4 +982,9 @@ pub enum ParserError {
     Tag,
     TakeWhile,
     MapRes,
+    Satisfy,
+    OneOf,
+    NoneOf,
+    Char,
+    TagNoCase,
 }
@@ -0,0 +1,154 @@
+use crate::*;
+
+/// Fluent combinator methods for anything that behaves like a parser.
+///
+/// Building a grammar out of the free functions in this crate quickly turns
+/// into deeply nested calls like `map(pair(&p, left(...)), join)`. Any
+/// closure matching `Fn(&'a str) -> ParseResult<'a, O>` implements this trait,
+/// so the same grammar can be written as a left-to-right chain instead.
+///
+/// # Example
+/// ```rust
+/// use comb::*;
+///
+/// let parser = tag("a").then(tag("b")).map(|(a, b)| format!("{}{}", a, b));
+///
+/// assert_eq!(parser.parse("ab"), Ok(("", "ab".to_string())));
+/// assert_eq!(parser.parse("ac").unwrap_err().kind, ParserError::Tag);
+/// ```
+pub trait Parser<'a, O> {
+    fn parse(&self, i: &'a str) -> ParseResult<'a, O>;
+
+    /// See the free function [`map`].
+    fn map<F, B>(self, f: F) -> BoxedParser<'a, B>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        B: 'a,
+        F: Fn(O) -> B + 'a,
+    {
+        BoxedParser::new(map(move |i| self.parse(i), f))
+    }
+
+    /// See the free function [`map_res`].
+    fn map_res<F, B, E>(self, f: F) -> BoxedParser<'a, B>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        B: 'a,
+        E: 'a,
+        F: Fn(O) -> Result<B, E> + 'a,
+    {
+        BoxedParser::new(map_res(move |i| self.parse(i), f))
+    }
+
+    /// See the free function [`opt`].
+    fn opt(self) -> BoxedParser<'a, Option<O>>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+    {
+        BoxedParser::new(opt(move |i| self.parse(i)))
+    }
+
+    /// See the free function [`many`].
+    fn many(self) -> BoxedParser<'a, Vec<O>>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+    {
+        BoxedParser::new(many(move |i| self.parse(i)))
+    }
+
+    /// See the free function [`either`].
+    fn or<P>(self, other: P) -> BoxedParser<'a, O>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        P: Parser<'a, O> + 'a,
+    {
+        BoxedParser::new(either(move |i| self.parse(i), move |i| other.parse(i)))
+    }
+
+    /// See the free function [`pair`].
+    fn then<P, O2>(self, other: P) -> BoxedParser<'a, (O, O2)>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        O2: 'a,
+        P: Parser<'a, O2> + 'a,
+    {
+        BoxedParser::new(pair(move |i| self.parse(i), move |i| other.parse(i)))
+    }
+
+    /// See the free function [`left`].
+    fn left<P, O2>(self, other: P) -> BoxedParser<'a, O>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        O2: 'a,
+        P: Parser<'a, O2> + 'a,
+    {
+        BoxedParser::new(left(move |i| self.parse(i), move |i| other.parse(i)))
+    }
+
+    /// See the free function [`right`].
+    fn right<P, O2>(self, other: P) -> BoxedParser<'a, O2>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+        O2: 'a,
+        P: Parser<'a, O2> + 'a,
+    {
+        BoxedParser::new(right(move |i| self.parse(i), move |i| other.parse(i)))
+    }
+
+    /// Runs the parser and requires that it consumes the input in full,
+    /// rejecting anything left over with `ParserError::Eof`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use comb::*;
+    ///
+    /// assert_eq!(tag("hi").parse_complete("hi"), Ok("hi"));
+    /// assert_eq!(tag("hi").parse_complete("hi there").unwrap_err().kind, ParserError::Eof);
+    /// ```
+    fn parse_complete(self, i: &'a str) -> Result<O, ParseError<'a>>
+    where
+        Self: Sized + 'a,
+        O: 'a,
+    {
+        left(move |i| self.parse(i), eoi)(i).map(|(_, r)| r)
+    }
+}
+
+impl<'a, O, F> Parser<'a, O> for F
+where
+    F: Fn(&'a str) -> ParseResult<'a, O>,
+{
+    fn parse(&self, i: &'a str) -> ParseResult<'a, O> {
+        self(i)
+    }
+}
+
+/// A boxed-up `Parser`, so grammars that recurse into themselves (or just
+/// want to store a handful of alternatives in a `Vec`) have something with a
+/// concrete, nameable type to hold on to.
+pub struct BoxedParser<'a, O> {
+    parser: Box<dyn Fn(&'a str) -> ParseResult<'a, O> + 'a>,
+}
+
+impl<'a, O> BoxedParser<'a, O> {
+    pub fn new<P>(p: P) -> Self
+    where
+        P: Fn(&'a str) -> ParseResult<'a, O> + 'a,
+    {
+        BoxedParser { parser: Box::new(p) }
+    }
+}
+
+impl<'a, O> Parser<'a, O> for BoxedParser<'a, O> {
+    fn parse(&self, i: &'a str) -> ParseResult<'a, O> {
+        (self.parser)(i)
+    }
+}
@@ -1,3 +1,4 @@
+use comb::*;
 use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
@@ -5,24 +6,31 @@ use std::path::{Path, PathBuf};
 mod queue;
 mod resolve;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModuleKind {
+    CommonJs,
+    EsModule,
+}
+
 #[derive(Debug, Clone)]
 struct Module {
     source: String,
+    kind: ModuleKind,
     deps: HashMap<String, PathBuf>,
 }
 
 pub fn bundle(file: String, root: &Path) -> Result<String, Box<dyn std::error::Error>> {
     let entry = resolve::resolve(file, &root).ok_or("No entry point")?;
-    let regexp = regex::Regex::new(r#"require\s*\(\s*['"](.+?)['"]\s*\)"#)?;
     let modules = queue::run(entry.clone(), |path| {
         let source = read_to_string(&path)?;
+        let scanned = scan(&source);
 
-        let deps = regexp.captures_iter(&source).map(|dep| {
-            (dep[1].to_string(), resolve::resolve(dep[1].to_string(), &path.parent().unwrap()).unwrap())
-        }).collect::<HashMap::<String, PathBuf>>();
+        let deps = scanned.deps.iter().map(|dep| {
+            (dep.clone(), resolve::resolve(dep.clone(), &path.parent().unwrap()).unwrap())
+        }).collect::<HashMap<String, PathBuf>>();
         let modules = deps.values().cloned().collect();
 
-        Ok((Module { source, deps }, modules))
+        Ok((Module { source: scanned.source, kind: scanned.kind, deps }, modules))
     })?;
     let content = write(&modules, &entry);
 
@@ -35,8 +43,12 @@ fn write(modules: &HashMap<PathBuf, Module>, entry_point: &Path) -> String {
         let deps = json::stringify(module.deps.iter().map(|(dep, path)|
             (dep.to_string(), modules.keys().position(|v| v == path).unwrap())
         ).collect::<HashMap::<String, usize>>());
+        let interop = match module.kind {
+            ModuleKind::EsModule => "Object.defineProperty(exports, '__esModule', { value: true });\n",
+            ModuleKind::CommonJs => "",
+        };
 
-        format!("__deps[{}] = {{ deps: {}, func: function(module, exports, require) {{\n{} \n}} }};", filename, deps, module.source)
+        format!("__deps[{}] = {{ deps: {}, func: function(module, exports, require) {{\n{}{} \n}} }};", filename, deps, interop, module.source)
     }).collect::<Vec<String>>().join("\n");
 
     let prelude = include_str!("prelude.js");
@@ -44,6 +56,271 @@ fn write(modules: &HashMap<PathBuf, Module>, entry_point: &Path) -> String {
     format!("{}; {}; __req(null)({}) }})()", prelude, mods, entry_id)
 }
 
+/// The result of scanning a module's source for dependencies: the flavor of
+/// module it turned out to be, the specifiers it depends on, and (for ES
+/// modules) the source rewritten into the CommonJS shape the runtime in
+/// `prelude.js` already knows how to run.
+struct Scanned {
+    kind: ModuleKind,
+    deps: Vec<String>,
+    source: String,
+}
+
+/// Walks `source` looking for `require("x")` calls and `import`/`export`
+/// statements, skipping over string literals and comments so neither kind of
+/// dependency is found where it isn't (inside a string, say, or commented
+/// out). Built out of `comb` rather than a single regex so it can see ES
+/// module syntax, which a `require(...)` pattern has no way to express.
+fn scan(source: &str) -> Scanned {
+    let mut deps = Vec::new();
+    let mut kind = ModuleKind::CommonJs;
+    let mut hoisted_exports = Vec::new();
+    let mut out = String::with_capacity(source.len());
+    let mut i = source;
+
+    while !i.is_empty() {
+        if let Ok((rest, text)) = skip_string(i) {
+            out.push_str(text);
+            i = rest;
+        } else if let Ok((rest, text)) = skip_line_comment(i) {
+            out.push_str(text);
+            i = rest;
+        } else if let Ok((rest, text)) = skip_block_comment(i) {
+            out.push_str(text);
+            i = rest;
+        } else if let Ok((rest, text)) = require_call(i) {
+            if let Ok((_, specifier)) = require_specifier(i) {
+                deps.push(specifier.to_string());
+            }
+            out.push_str(text);
+            i = rest;
+        } else if let Ok((rest, clause)) = import_statement(i) {
+            kind = ModuleKind::EsModule;
+            match rewrite_import(clause) {
+                Some((specifier, code)) => {
+                    deps.push(specifier);
+                    out.push_str(&code);
+                }
+                None => out.push_str(clause),
+            }
+            i = rest;
+        } else if let Ok((rest, clause)) = export_statement(i) {
+            kind = ModuleKind::EsModule;
+            match rewrite_export(clause) {
+                Some((specifier, code, hoist)) => {
+                    deps.extend(specifier);
+                    out.push_str(&code);
+                    hoisted_exports.extend(hoist);
+                }
+                None => out.push_str(clause),
+            }
+            i = rest;
+        } else {
+            let mut chars = i.chars();
+            out.push(chars.next().unwrap());
+            i = chars.as_str();
+        }
+    }
+
+    for name in &hoisted_exports {
+        out.push_str(&format!("\nmodule.exports.{0} = {0};", name));
+    }
+
+    Scanned { kind, deps, source: out }
+}
+
+/// Matches a single-, double- or backtick-quoted string literal, returning the
+/// whole thing (quotes included) so callers can copy it through untouched.
+fn skip_string<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    let double = recognize(middle(tag("\""), opt(take_while(|c| c != '"')), tag("\"")));
+    let single = recognize(middle(tag("'"), opt(take_while(|c| c != '\'')), tag("'")));
+    let backtick = recognize(middle(tag("`"), opt(take_while(|c| c != '`')), tag("`")));
+    either(either(double, single), backtick)(i)
+}
+
+/// Matches a `// ...` line comment, up to (but not including) the newline.
+fn skip_line_comment<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    recognize(pair(tag("//"), opt(take_while(|c| c != '\n'))))(i)
+}
+
+/// Matches a `/* ... */` block comment. Forgiving of an unterminated comment,
+/// which then runs to the end of the input.
+fn skip_block_comment<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    let (rest, _) = tag("/*")(i)?;
+    let len = rest.find("*/").map_or(i.len(), |x| 2 + x + 2);
+    Ok((&i[len..], &i[..len]))
+}
+
+/// Matches a single- or double-quoted string, returning its contents without
+/// the surrounding quotes.
+fn quoted<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    let double = middle(tag("\""), opt(take_while(|c| c != '"')), tag("\""));
+    let single = middle(tag("'"), opt(take_while(|c| c != '\'')), tag("'"));
+    map(either(double, single), |s: Option<&str>| s.unwrap_or(""))(i)
+}
+
+/// Matches a whole `require("x")` call expression.
+fn require_call<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    recognize(middle(
+        trio(tag("require"), whitespace, tag("(")),
+        w(quoted),
+        pair(whitespace, tag(")")),
+    ))(i)
+}
+
+/// Pulls just the specifier out of a `require("x")` call.
+fn require_specifier<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    middle(trio(tag("require"), whitespace, tag("(")), w(quoted), pair(whitespace, tag(")")))(i)
+}
+
+/// Matches a whole `import ...` statement, up to (but not including) the
+/// closing `;` or newline, tracking brace/paren/bracket depth so a clause
+/// like `import {\n  a,\n  b\n} from "x"` isn't cut off at the first
+/// newline inside its braces.
+fn import_statement<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    let (_, matched) = keyword("import")(i)?;
+    let len = matched.len() + statement_tail_len(&i[matched.len()..]);
+    Ok((&i[len..], &i[..len]))
+}
+
+/// Matches a whole `export ...` statement, up to (but not including) the
+/// closing `;` or newline, tracking brace/paren/bracket depth so a clause
+/// like `export {\n  a,\n  b\n}` isn't cut off at the first newline inside
+/// its braces.
+fn export_statement<'a>(i: &'a str) -> ParseResult<'a, &'a str> {
+    let (_, matched) = keyword("export")(i)?;
+    let len = matched.len() + statement_tail_len(&i[matched.len()..]);
+    Ok((&i[len..], &i[..len]))
+}
+
+/// Finds the length of the rest of a statement after its leading keyword: up
+/// to a top-level `;` or newline, where "top-level" means outside any
+/// `{}`/`()`/`[]` nesting and outside any string literal. Forgiving of a
+/// statement that runs off the end of the input: it then takes all of it.
+fn statement_tail_len(i: &str) -> usize {
+    let mut depth = 0i32;
+    let mut rest = i;
+    while !rest.is_empty() {
+        if let Ok((next, _)) = skip_string(rest) {
+            rest = next;
+            continue;
+        }
+        let mut chars = rest.chars();
+        let c = chars.next().unwrap();
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            ';' | '\n' if depth <= 0 => return i.len() - rest.len(),
+            _ => {}
+        }
+        rest = chars.as_str();
+    }
+    i.len()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Matches a fixed keyword, but only if it isn't just the prefix of a longer
+/// identifier (so `import` doesn't also swallow `importantConfig`).
+fn keyword<'a>(word: &'static str) -> impl Fn(&'a str) -> ParseResult<'a, &'a str> {
+    move |i| {
+        let (rest, matched) = tag(word)(i)?;
+        match rest.chars().next() {
+            Some(c) if is_ident_char(c) => Err(ParseError::new(i, ParserError::Tag)),
+            _ => Ok((rest, matched)),
+        }
+    }
+}
+
+/// Rewrites a captured `import ...` clause into the CommonJS call the
+/// runtime already understands, returning the dependency specifier alongside
+/// the rewritten code. `import "x"`, `import Name from "x"`,
+/// `import { a, b } from "x"` and `import * as ns from "x"` are all handled;
+/// anything else is left for the caller to pass through untouched.
+fn rewrite_import(clause: &str) -> Option<(String, String)> {
+    if let Ok((_, specifier)) = right(pair(keyword("import"), whitespace), quoted)(clause) {
+        return Some((specifier.to_string(), format!("require(\"{}\")", specifier)));
+    }
+
+    let from = |p| right(pair(whitespace, tag("from")), w(p));
+
+    if let Ok((_, (ns, specifier))) = right(
+        pair(keyword("import"), whitespace),
+        pair(right(pair(tag("* as"), whitespace), take_while(is_ident_char)), from(quoted)),
+    )(clause)
+    {
+        return Some((specifier.to_string(), format!("const {} = require(\"{}\")", ns, specifier)));
+    }
+
+    if let Ok((_, (bindings, specifier))) = right(
+        pair(keyword("import"), whitespace),
+        pair(recognize(middle(tag("{"), opt(take_while(|c| c != '}')), tag("}"))), from(quoted)),
+    )(clause)
+    {
+        return Some((specifier.to_string(), format!("const {} = require(\"{}\")", bindings, specifier)));
+    }
+
+    if let Ok((_, (name, specifier))) = right(
+        pair(keyword("import"), whitespace),
+        pair(take_while(is_ident_char), from(quoted)),
+    )(clause)
+    {
+        return Some((specifier.to_string(), format!("const {} = require(\"{}\").default", name, specifier)));
+    }
+
+    None
+}
+
+/// Rewrites a captured `export ...` clause into its CommonJS equivalent,
+/// returning the dependency specifier (for a re-export only), the
+/// replacement text and, for a named declaration, the name to export once
+/// the declaration has actually run. Handles re-exports
+/// (`export ... from "x"`), `export default`, `export
+/// const/let/var/function/class name`, and bare `export { a, b }`; anything
+/// else is left for the caller to pass through untouched.
+///
+/// Named declarations only have their `export ` prefix stripped here: the
+/// matching `module.exports.name = name` assignment is appended by the caller
+/// at the end of the module, after every declaration in it has run.
+fn rewrite_export(clause: &str) -> Option<(Option<String>, String, Option<String>)> {
+    if let Ok((_, specifier)) =
+        right(pair(keyword("export"), take_until("from")), right(pair(tag("from"), whitespace), w(quoted)))(clause)
+    {
+        return Some((Some(specifier.to_string()), format!("Object.assign(module.exports, require(\"{}\"))", specifier), None));
+    }
+
+    if let Ok((_, bindings)) =
+        right(pair(keyword("export"), whitespace), recognize(middle(tag("{"), opt(take_while(|c| c != '}')), tag("}"))))(clause)
+    {
+        return Some((None, format!("Object.assign(module.exports, {})", bindings), None));
+    }
+
+    let rest = clause.strip_prefix("export").map(str::trim_start)?;
+
+    if let Some(expr) = rest.strip_prefix("default") {
+        return Some((None, format!("module.exports.default ={}", expr), None));
+    }
+
+    for decl in ["const", "let", "var", "function", "class"] {
+        if let Some(after_keyword) = rest.strip_prefix(decl) {
+            let name_start = after_keyword.find(|c: char| !c.is_whitespace() && c != '*')?;
+            let name_rest = &after_keyword[name_start..];
+            let name_end = name_rest.find(|c| !is_ident_char(c)).unwrap_or(name_rest.len());
+            if name_end == 0 {
+                // Not a plain identifier (e.g. `export const { a, b } = ...`
+                // destructuring) -- nothing sane to hoist, so pass through.
+                return None;
+            }
+            let name = name_rest[..name_end].to_string();
+            return Some((None, format!("{}{}", decl, after_keyword), Some(name)));
+        }
+    }
+
+    None
+}
+
 #[test]
 fn test_bundler() {
     fn assert_bundle(path: &str, substring: &str) {
@@ -65,4 +342,11 @@ fn test_bundler() {
     assert_node("with-dep", "2");
     assert_node("double-quotes", "");
     assert_node("crazy-indent", "");
+
+    assert_bundle("esm-default-import", "require(\"./math.js\").default");
+    assert_bundle("esm-named-import", "const { square } = require(\"./math.js\")");
+    assert_bundle("esm-namespace-import", "const math = require(\"./math.js\")");
+    assert_bundle("esm-bare-import", "require(\"./polyfill.js\")");
+    assert_bundle("esm-export-from", "Object.assign(module.exports, require(\"./math.js\"))");
+    assert_node("esm-default-import", "4");
 }